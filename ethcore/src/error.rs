@@ -0,0 +1,91 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Errors raised while handling private transactions.
+
+use std::fmt;
+use std::io;
+use serde_json;
+
+/// Errors concerning the private transactions verification/signing stores.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PrivateTransactionError {
+	/// Private transactions queue is full.
+	QueueIsFull,
+	/// Same private transaction has already been imported.
+	PrivateTransactionAlreadyImported,
+	/// No private transaction found for the requested hash.
+	PrivateTransactionNotFound,
+	/// Signer is not one of the validators for this private transaction.
+	NotAValidator,
+	/// A signature from this validator has already been recorded.
+	SignatureAlreadyReceived,
+	/// Validator's reported state does not byte-match the locally computed state.
+	StateMismatch,
+}
+
+impl fmt::Display for PrivateTransactionError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let msg = match *self {
+			PrivateTransactionError::QueueIsFull => "Private transactions queue is full",
+			PrivateTransactionError::PrivateTransactionAlreadyImported => "Private transaction is already imported",
+			PrivateTransactionError::PrivateTransactionNotFound => "Private transaction not found",
+			PrivateTransactionError::NotAValidator => "Signer is not a validator for this private transaction",
+			PrivateTransactionError::SignatureAlreadyReceived => "Signature from this validator has already been received",
+			PrivateTransactionError::StateMismatch => "Validator's reported state does not match the locally computed state",
+		};
+		f.write_str(msg)
+	}
+}
+
+/// Top-level error type for the private transactions subsystem.
+#[derive(Debug)]
+pub enum Error {
+	/// Error concerning a private transaction store.
+	PrivateTransaction(PrivateTransactionError),
+	/// I/O error encountered while persisting the private transaction journal.
+	Io(io::Error),
+	/// Error (de)serializing a private transaction journal entry as JSON.
+	Json(serde_json::Error),
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Error::PrivateTransaction(ref err) => err.fmt(f),
+			Error::Io(ref err) => err.fmt(f),
+			Error::Json(ref err) => err.fmt(f),
+		}
+	}
+}
+
+impl From<PrivateTransactionError> for Error {
+	fn from(err: PrivateTransactionError) -> Error {
+		Error::PrivateTransaction(err)
+	}
+}
+
+impl From<io::Error> for Error {
+	fn from(err: io::Error) -> Error {
+		Error::Io(err)
+	}
+}
+
+impl From<serde_json::Error> for Error {
+	fn from(err: serde_json::Error) -> Error {
+		Error::Json(err)
+	}
+}
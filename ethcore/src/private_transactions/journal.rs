@@ -0,0 +1,298 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Persistent journal of private transaction lifecycle events.
+//!
+//! Neither `VerificationStore` nor `SigningStore` keeps any history once a
+//! private transaction is removed, so this module keeps a disk-backed record
+//! of the state transitions a private transaction goes through, keyed by
+//! `private_hash`. Entries older than the configured maximum age, or beyond the
+//! configured maximum count for a hash, are trimmed from the record.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, Duration};
+
+use serde_json;
+use bigint::hash::H256;
+use error::Error;
+
+/// Default number of entries retained per private transaction.
+const MAX_JOURNAL_LEN: usize = 10;
+
+/// Default maximum age, in seconds, an entry is allowed to live in the journal.
+const MAX_JOURNAL_AGE_SECS: u64 = 60 * 60 * 24 * 30; // 30 days
+
+/// A step in the lifecycle of a private transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransactionState {
+	/// Private transaction has been received and queued for verification.
+	Created,
+	/// Private transaction has passed verification.
+	Validation,
+	/// A validator's signature for the resulting state has been received.
+	Signed,
+	/// The resulting public transaction has been deployed to the chain.
+	Deployed,
+}
+
+/// A single recorded state transition, stamped with the time it was observed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogEntry {
+	/// Hash of the private transaction the entry belongs to.
+	pub private_hash: H256,
+	/// State the private transaction transitioned into.
+	pub state: TransactionState,
+	/// Time the transition was recorded, guaranteed to be monotonically
+	/// increasing across restarts of the journal.
+	pub creation_time: SystemTime,
+}
+
+/// Configuration for the persistent transaction journal.
+#[derive(Debug, Clone)]
+pub struct LogsConfig {
+	/// Directory entries are persisted to.
+	pub logs_path: PathBuf,
+	/// Maximum number of entries retained per private transaction.
+	pub max_count: usize,
+	/// Maximum age of an entry before it is trimmed from the journal.
+	pub max_age: Duration,
+}
+
+impl LogsConfig {
+	/// Creates a config that persists into `logs_path` with default limits.
+	pub fn new(logs_path: PathBuf) -> Self {
+		LogsConfig {
+			logs_path: logs_path,
+			max_count: MAX_JOURNAL_LEN,
+			max_age: Duration::from_secs(MAX_JOURNAL_AGE_SECS),
+		}
+	}
+}
+
+/// Journal recording the lifecycle of private transactions, persisted as JSON
+/// to a configurable directory so the history survives restarts.
+pub struct Logging {
+	config: LogsConfig,
+	logs: HashMap<H256, Vec<LogEntry>>,
+	last_time: SystemTime,
+}
+
+impl Logging {
+	/// Creates a new journal, loading any entries already persisted under
+	/// `config.logs_path` and seeding the time source so that newly recorded
+	/// entries are guaranteed to be newer than anything already on disk.
+	pub fn new(config: LogsConfig) -> Result<Self, Error> {
+		fs::create_dir_all(&config.logs_path)?;
+		let mut logs = HashMap::new();
+		let mut latest = SystemTime::now();
+		for entry in fs::read_dir(&config.logs_path)? {
+			let entry = entry?;
+			let mut contents = String::new();
+			fs::File::open(entry.path())?.read_to_string(&mut contents)?;
+			let entries: Vec<LogEntry> = match serde_json::from_str(&contents) {
+				Ok(entries) => entries,
+				Err(_) => continue,
+			};
+			for entry in &entries {
+				if entry.creation_time > latest {
+					latest = entry.creation_time;
+				}
+			}
+			if let Some(hash) = entries.get(0).map(|e| e.private_hash) {
+				logs.insert(hash, entries);
+			}
+		}
+		let mut journal = Logging {
+			config: config,
+			logs: logs,
+			last_time: latest,
+		};
+		journal.prune_expired()?;
+		Ok(journal)
+	}
+
+	/// Next monotonic timestamp, guaranteed to be greater than every
+	/// timestamp handed out before it, even if the wall clock moves backwards.
+	fn next_time(&mut self) -> SystemTime {
+		let now = SystemTime::now();
+		let next = if now > self.last_time { now } else { self.last_time + Duration::from_nanos(1) };
+		self.last_time = next;
+		next
+	}
+
+	/// Records that `private_hash` transitioned into `state`, trimming and
+	/// persisting the updated log for that hash.
+	pub fn add_transition(&mut self, private_hash: H256, state: TransactionState) -> Result<(), Error> {
+		let time = self.next_time();
+		let max_age = self.config.max_age;
+		let max_count = self.config.max_count;
+		let entries = self.logs.entry(private_hash).or_insert_with(Vec::new);
+		entries.push(LogEntry {
+			private_hash: private_hash,
+			state: state,
+			creation_time: time,
+		});
+		entries.retain(|entry| time.duration_since(entry.creation_time).map(|age| age <= max_age).unwrap_or(true));
+		let len = entries.len();
+		if len > max_count {
+			entries.drain(0..len - max_count);
+		}
+		let path = self.path_for(&private_hash);
+		let contents = serde_json::to_string(entries)?;
+		fs::File::create(path)?.write_all(contents.as_bytes())?;
+		// A terminal hash (e.g. `Deployed`) is never written to again, so opportunistic
+		// trimming tied to this write alone would never revisit it; sweep the whole
+		// on-disk journal here too, so every hash eventually ages out
+		self.prune_expired()
+	}
+
+	/// Deletes entries (and, once a hash's log is empty, its on-disk file) older than
+	/// `max_age` across the whole journal, not just the hash that triggered the sweep.
+	/// Called on load and on every `add_transition`, so hashes that reach a terminal
+	/// state and are never written to again still eventually get pruned.
+	pub fn prune_expired(&mut self) -> Result<(), Error> {
+		let max_age = self.config.max_age;
+		let now = self.last_time;
+		let hashes: Vec<H256> = self.logs.keys().cloned().collect();
+		for hash in hashes {
+			let changed = {
+				let entries = self.logs.get_mut(&hash).expect("hash was just read from self.logs.keys()");
+				let before = entries.len();
+				entries.retain(|entry| now.duration_since(entry.creation_time).map(|age| age <= max_age).unwrap_or(true));
+				entries.len() != before
+			};
+			let is_empty = self.logs.get(&hash).map(|entries| entries.is_empty()).unwrap_or(false);
+			if is_empty {
+				self.logs.remove(&hash);
+				let _ = fs::remove_file(self.path_for(&hash));
+			} else if changed {
+				let contents = serde_json::to_string(&self.logs[&hash])?;
+				fs::File::create(self.path_for(&hash))?.write_all(contents.as_bytes())?;
+			}
+		}
+		Ok(())
+	}
+
+	/// Returns the recorded log for a given private transaction hash.
+	pub fn transaction_log(&self, private_hash: &H256) -> Option<&[LogEntry]> {
+		self.logs.get(private_hash).map(|entries| entries.as_slice())
+	}
+
+	/// Returns up to `limit` of the most recently recorded entries across all
+	/// private transactions, newest first.
+	pub fn recent_history(&self, limit: usize) -> Vec<LogEntry> {
+		let mut all: Vec<LogEntry> = self.logs.values().flat_map(|entries| entries.iter().cloned()).collect();
+		all.sort_by(|a, b| b.creation_time.cmp(&a.creation_time));
+		all.truncate(limit);
+		all
+	}
+
+	fn path_for(&self, private_hash: &H256) -> PathBuf {
+		self.config.logs_path.join(format!("{:x}.json", private_hash))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::fs;
+
+	fn temp_logs_path(name: &str) -> PathBuf {
+		::std::env::temp_dir().join(format!("private-tx-journal-test-{}-{:x}", name, H256::random()))
+	}
+
+	#[test]
+	fn persists_and_reloads_entries() {
+		let path = temp_logs_path("roundtrip");
+		let hash = H256::random();
+		{
+			let mut journal = Logging::new(LogsConfig::new(path.clone())).unwrap();
+			journal.add_transition(hash, TransactionState::Created).unwrap();
+			journal.add_transition(hash, TransactionState::Validation).unwrap();
+		}
+
+		let reloaded = Logging::new(LogsConfig::new(path.clone())).unwrap();
+		let log = reloaded.transaction_log(&hash).unwrap();
+		assert_eq!(log.len(), 2);
+		assert_eq!(log[0].state, TransactionState::Created);
+		assert_eq!(log[1].state, TransactionState::Validation);
+
+		fs::remove_dir_all(&path).ok();
+	}
+
+	#[test]
+	fn next_time_is_monotonic_across_restart() {
+		let path = temp_logs_path("monotonic");
+		let hash = H256::random();
+		let last_time_before_restart = {
+			let mut journal = Logging::new(LogsConfig::new(path.clone())).unwrap();
+			journal.add_transition(hash, TransactionState::Created).unwrap();
+			journal.last_time
+		};
+
+		let reloaded = Logging::new(LogsConfig::new(path.clone())).unwrap();
+		assert!(reloaded.last_time >= last_time_before_restart);
+
+		fs::remove_dir_all(&path).ok();
+	}
+
+	#[test]
+	fn trims_by_max_count() {
+		let path = temp_logs_path("max-count");
+		let hash = H256::random();
+		let mut config = LogsConfig::new(path.clone());
+		config.max_count = 2;
+		let mut journal = Logging::new(config).unwrap();
+
+		journal.add_transition(hash, TransactionState::Created).unwrap();
+		journal.add_transition(hash, TransactionState::Validation).unwrap();
+		journal.add_transition(hash, TransactionState::Signed).unwrap();
+
+		let log = journal.transaction_log(&hash).unwrap();
+		assert_eq!(log.len(), 2);
+		assert_eq!(log[0].state, TransactionState::Validation);
+		assert_eq!(log[1].state, TransactionState::Signed);
+
+		fs::remove_dir_all(&path).ok();
+	}
+
+	#[test]
+	fn prune_expired_removes_hashes_that_are_never_written_to_again() {
+		let path = temp_logs_path("prune");
+		let stale_hash = H256::random();
+		let fresh_hash = H256::random();
+		let mut config = LogsConfig::new(path.clone());
+		config.max_age = Duration::from_nanos(1);
+		let mut journal = Logging::new(config).unwrap();
+
+		// Reaches a terminal state and is never written to again, unlike `fresh_hash` below
+		journal.add_transition(stale_hash, TransactionState::Deployed).unwrap();
+		journal.add_transition(fresh_hash, TransactionState::Created).unwrap();
+
+		// A later sweep (triggered here by another hash's transition, same as it would be by
+		// the next restart's `Logging::new`) must still catch `stale_hash`, even though nothing
+		// was ever recorded against it again
+		journal.add_transition(fresh_hash, TransactionState::Validation).unwrap();
+
+		assert!(journal.transaction_log(&stale_hash).is_none());
+		assert!(!path.join(format!("{:x}.json", stale_hash)).exists());
+
+		fs::remove_dir_all(&path).ok();
+	}
+}
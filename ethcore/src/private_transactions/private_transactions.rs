@@ -23,7 +23,11 @@ use bigint::prelude::U256;
 use bigint::hash::H256;
 use transaction::{UnverifiedTransaction, SignedTransaction};
 use miner::{TransactionQueue, TransactionQueueDetailsProvider, TransactionOrigin, RemovalReason};
+use miner::transaction_queue::PrioritizationStrategy;
+use txpool;
 use header::BlockNumber;
+use heapsize::HeapSizeOf;
+use super::journal::{Logging, TransactionState};
 
 /// Maximum length for private transactions queues.
 const MAX_QUEUE_LEN: usize = 8312;
@@ -37,27 +41,83 @@ pub struct PrivateTransactionDesc {
 	pub contract: Address,
 	/// Address that should be used for verification
 	pub validator_account: Address,
+	/// Block number the transaction was inserted at, used to cull stale backlog
+	pub insertion_time: BlockNumber,
+	/// Sender of the original transaction, used so `cull` can find this entry even when it
+	/// is not its sender's top (nonce-ready) transaction in the queue
+	pub sender: Address,
+	/// Nonce of the original transaction, used by `cull` to prune entries the sender has
+	/// already superseded on-chain
+	pub nonce: U256,
+}
+
+impl HeapSizeOf for PrivateTransactionDesc {
+	fn heap_size_of_children(&self) -> usize {
+		0
+	}
+}
+
+/// Admission-control limits applied to the verification queue.
+#[derive(Debug, Clone, Copy)]
+pub struct QueueLimits {
+	/// Maximum number of pending private transactions tracked for a single sender.
+	pub max_per_sender: usize,
+	/// Maximum number of pending private transactions tracked across all senders.
+	pub max_count: usize,
+	/// Maximum combined memory, in bytes, the underlying transaction queue is allowed to occupy.
+	pub max_mem_usage: usize,
+	/// Maximum number of blocks a transaction is allowed to sit in the queue before `cull` drops it.
+	pub max_age: BlockNumber,
+}
+
+impl Default for QueueLimits {
+	fn default() -> Self {
+		QueueLimits {
+			max_per_sender: 1,
+			max_count: MAX_QUEUE_LEN,
+			max_mem_usage: 8 * 1024 * 1024,
+			max_age: 50,
+		}
+	}
 }
 
 /// Storage for private transactions for verification
 pub struct VerificationStore {
 	/// Descriptors for private transactions in queue for verification with key - hash of the original transaction
 	descriptors: HashMap<H256, PrivateTransactionDesc>,
-	/// Queue with transactions for verification
+	/// Queue with transactions for verification, scored by gas price so that
+	/// higher-fee private transactions can displace lower-fee ones once full
 	transactions: TransactionQueue,
+	/// Heap size budget, in bytes, for the descriptors and queued transactions tracked by this store
+	max_mem_usage: usize,
+	/// Maximum number of blocks a transaction may sit in the queue before `cull` drops it
+	max_age: BlockNumber,
 }
 
 impl VerificationStore {
-	/// Creates new store
-	pub fn new() -> Self {
+	/// Creates new store, admitting transactions under the given per-sender,
+	/// total-count and memory limits
+	pub fn new(limits: QueueLimits) -> Self {
+		let options = txpool::Options {
+			max_count: limits.max_count,
+			max_per_sender: limits.max_per_sender,
+			max_mem_usage: limits.max_mem_usage,
+		};
 		VerificationStore {
-			transactions: TransactionQueue::default(),
+			transactions: TransactionQueue::with_limits(options, PrioritizationStrategy::GasPriceOnly),
 			descriptors: HashMap::new(),
+			max_mem_usage: limits.max_mem_usage,
+			max_age: limits.max_age,
 		}
 	}
 
+	/// Heap size, in bytes, currently used by the descriptors and queued transactions in this store
+	pub fn mem_usage(&self) -> usize {
+		self.descriptors.heap_size_of_children() + self.transactions.mem_usage()
+	}
+
 	/// Adds private transaction for verification into the store
-	pub fn add_transaction(
+	pub fn add_transaction<F>(
 		&mut self,
 		transaction: UnverifiedTransaction,
 		contract: Address,
@@ -65,26 +125,101 @@ impl VerificationStore {
 		private_hash: H256,
 		details_provider: &TransactionQueueDetailsProvider,
 		insertion_time: BlockNumber,
-	) -> Result<(), Error> {
-		if self.descriptors.len() > MAX_QUEUE_LEN {
-			return Err(PrivateTransactionError::QueueIsFull.into());
-		}
-
+		journal: &mut Logging,
+		fetch_nonce: &F,
+	) -> Result<(), Error>
+		where F: Fn(&Address) -> U256
+	{
 		if self.descriptors.get(&transaction.hash()).is_some() {
 			return Err(PrivateTransactionError::PrivateTransactionAlreadyImported.into());
 		}
 		let transaction_hash = transaction.hash();
 		let signed_transaction = SignedTransaction::new(transaction)?;
-		match self.transactions.add(signed_transaction, TransactionOrigin::External, insertion_time, None, details_provider) {
+		let incoming_sender = signed_transaction.sender();
+		let incoming_nonce = signed_transaction.nonce;
+		let incoming_gas_price = signed_transaction.gas_price;
+		let add_result = self.transactions.add(signed_transaction.clone(), TransactionOrigin::External, insertion_time, None, details_provider);
+		let add_result = match add_result {
+			Err(_) if self.evict_lowest_scored(incoming_sender, incoming_gas_price, fetch_nonce) => {
+				self.transactions.add(signed_transaction, TransactionOrigin::External, insertion_time, None, details_provider)
+			}
+			other => other,
+		};
+		match add_result {
 			Ok(_) => {
 				self.descriptors.insert(transaction_hash, PrivateTransactionDesc{
 					private_hash: private_hash,
 					contract: contract,
 					validator_account: validator_account,
+					insertion_time: insertion_time,
+					sender: incoming_sender,
+					nonce: incoming_nonce,
 				});
+				// A journal write failure must not make an already-accepted transaction look
+				// rejected to the caller, so treat it as best-effort, same as `remove_private_transaction`
+				let _ = journal.add_transition(private_hash, TransactionState::Created);
+				// Trim under memory pressure: entries are count-bounded by the
+				// underlying queue already, but a run of large transactions can
+				// still push us over this store's own byte budget. The transaction just
+				// inserted above is excluded from eviction - otherwise a large, cheap
+				// payload could be evicted in the very call that queued it, leaving
+				// `Ok(())` to lie about the outcome to the caller
+				while self.mem_usage() > self.max_mem_usage {
+					if !self.evict_cheapest(&transaction_hash, fetch_nonce) {
+						break;
+					}
+				}
 				Ok(())
 			}
-			Err(e) => Err(e),
+			Err(_) => Err(PrivateTransactionError::QueueIsFull.into()),
+		}
+	}
+
+	/// Evicts a transaction to make room for `incoming_sender`'s transaction, but only if that
+	/// makes progress and only if `incoming_gas_price` actually outbids the victim.
+	///
+	/// If `incoming_sender` already has a transaction queued, the failed insert can only be its
+	/// own per-sender cap, so the victim *must* be that sender's own (cheaper) entry - evicting
+	/// some other sender's transaction would not free the incoming sender's slot and would just
+	/// destroy an unrelated queued transaction for no benefit. Only when the sender has nothing
+	/// queued yet (so the failure can only be the queue's total capacity) do we fall back to
+	/// evicting the globally cheapest entry. Returns `false` (and evicts nothing) if there is no
+	/// such victim or the incoming transaction is not a better bid than it.
+	fn evict_lowest_scored<F>(&mut self, incoming_sender: Address, incoming_gas_price: U256, fetch_nonce: &F) -> bool
+		where F: Fn(&Address) -> U256
+	{
+		let transactions = self.transactions.top_transactions();
+		let victim = transactions.iter().filter(|tx| tx.sender() == incoming_sender).min_by_key(|tx| tx.gas_price)
+			.or_else(|| transactions.iter().min_by_key(|tx| tx.gas_price));
+		match victim {
+			Some(victim) if victim.gas_price < incoming_gas_price => {
+				let hash = victim.hash();
+				self.descriptors.remove(&hash);
+				self.transactions.remove(&hash, fetch_nonce, RemovalReason::Dropped);
+				true
+			}
+			_ => false,
+		}
+	}
+
+	/// Evicts the lowest gas-priced transaction in the queue, along with its descriptor, to make
+	/// room under this store's own memory budget. `protected_hash` - the transaction just
+	/// inserted by the caller, if any - is never chosen as the victim, so a single
+	/// `add_transaction` call can never evict the very transaction it just queued.
+	/// Returns `false` if there is no other transaction left to evict.
+	fn evict_cheapest<F>(&mut self, protected_hash: &H256, fetch_nonce: &F) -> bool
+		where F: Fn(&Address) -> U256
+	{
+		let lowest = self.transactions.top_transactions().into_iter()
+			.filter(|tx| &tx.hash() != protected_hash)
+			.min_by_key(|tx| tx.gas_price).map(|tx| tx.hash());
+		match lowest {
+			Some(hash) => {
+				self.descriptors.remove(&hash);
+				self.transactions.remove(&hash, fetch_nonce, RemovalReason::Dropped);
+				true
+			}
+			None => false,
 		}
 	}
 
@@ -103,12 +238,45 @@ impl VerificationStore {
 	}
 
 	/// Remove transaction from the queue for verification
-	pub fn remove_private_transaction<F>(&mut self, transaction_hash: &H256, fetch_nonce: &F)
+	pub fn remove_private_transaction<F>(&mut self, transaction_hash: &H256, fetch_nonce: &F, journal: &mut Logging)
 		where F: Fn(&Address) -> U256 {
 
-		self.descriptors.remove(transaction_hash);
+		if let Some(desc) = self.descriptors.remove(transaction_hash) {
+			let _ = journal.add_transition(desc.private_hash, TransactionState::Validation);
+		}
 		self.transactions.remove(transaction_hash, fetch_nonce, RemovalReason::Invalid);
 	}
+
+	/// Prunes transactions whose sender's on-chain nonce has already advanced past the queued
+	/// nonce, and transactions that have been sitting in the queue longer than `max_age` blocks,
+	/// so permanently-stuck backlog doesn't crowd out legitimate high-fee private transactions.
+	///
+	/// Iterates `self.descriptors` directly rather than `self.transactions.top_transactions()`:
+	/// the latter returns only the one ready (lowest-nonce) transaction per sender, so with
+	/// `max_per_sender` configured above 1 any nonce-gapped entries for a sender would otherwise
+	/// never be visible here and could never be culled.
+	pub fn cull<F>(&mut self, current_block: BlockNumber, fetch_nonce: F)
+		where F: Fn(&Address) -> U256
+	{
+		let max_age = self.max_age;
+		let stale: Vec<H256> = self.descriptors.iter()
+			.filter(|&(_, desc)| {
+				let too_stale = current_block.saturating_sub(desc.insertion_time) > max_age;
+				desc.nonce < fetch_nonce(&desc.sender) || too_stale
+			})
+			.map(|(hash, _)| *hash)
+			.collect();
+		for hash in stale {
+			self.descriptors.remove(&hash);
+			self.transactions.remove(&hash, &fetch_nonce, RemovalReason::Dropped);
+		}
+	}
+}
+
+impl HeapSizeOf for VerificationStore {
+	fn heap_size_of_children(&self) -> usize {
+		self.mem_usage()
+	}
 }
 
 /// Desriptor for private transaction stored in queue for signing
@@ -118,26 +286,44 @@ pub struct PrivateTransactionSigningDesc {
 	pub original_transaction: SignedTransaction,
 	/// Supposed validators from the contract
 	pub validators: Vec<Address>,
-	/// Already obtained signatures
-	pub received_signatures: Vec<Signature>,
+	/// Already obtained signatures, keyed by the validator address that produced them
+	pub received_signatures: HashMap<Address, Signature>,
 	/// State after transaction execution to compare further with received from validators
 	pub state: Bytes,
 }
 
+impl HeapSizeOf for PrivateTransactionSigningDesc {
+	fn heap_size_of_children(&self) -> usize {
+		self.original_transaction.heap_size_of_children()
+			+ self.validators.heap_size_of_children()
+			+ self.received_signatures.heap_size_of_children()
+			+ self.state.heap_size_of_children()
+	}
+}
+
 /// Storage for private transactions for signing
 pub struct SigningStore {
 	/// Transactions and descriptors for signing
 	transactions: HashMap<H256, PrivateTransactionSigningDesc>,
+	/// Heap size budget, in bytes, for the descriptors tracked by this store
+	max_mem_usage: usize,
 }
 
 impl SigningStore {
-	/// Creates new store
-	pub fn new() -> Self {
+	/// Creates new store, rejecting any transaction whose descriptor would push
+	/// the tracked descriptors past `max_mem_usage` bytes
+	pub fn new(max_mem_usage: usize) -> Self {
 		SigningStore {
 			transactions: HashMap::new(),
+			max_mem_usage: max_mem_usage,
 		}
 	}
 
+	/// Heap size, in bytes, currently used by the descriptors in this store
+	pub fn mem_usage(&self) -> usize {
+		self.transactions.heap_size_of_children()
+	}
+
 	/// Adds new private transaction into the store for signing
 	pub fn add_transaction(
 		&mut self,
@@ -150,12 +336,17 @@ impl SigningStore {
 			return Err(PrivateTransactionError::QueueIsFull.into());
 		}
 
-		self.transactions.insert(private_hash, PrivateTransactionSigningDesc {
+		let desc = PrivateTransactionSigningDesc {
 			original_transaction: transaction.clone(),
 			validators: validators.clone(),
-			received_signatures: Vec::new(),
+			received_signatures: HashMap::new(),
 			state: state,
-		});
+		};
+		if self.mem_usage() + desc.heap_size_of_children() > self.max_mem_usage {
+			return Err(PrivateTransactionError::QueueIsFull.into());
+		}
+
+		self.transactions.insert(private_hash, desc);
 		Ok(())
 	}
 
@@ -170,12 +361,336 @@ impl SigningStore {
 		Ok(())
 	}
 
-	/// Adds received signature for the stored private transaction
-	pub fn add_signature(&mut self, private_hash: &H256, signature: Signature) -> Result<(), Error> {
-		let mut desc = self.transactions.get_mut(private_hash).ok_or_else(|| PrivateTransactionError::PrivateTransactionNotFound)?;
-		if !desc.received_signatures.contains(&signature) {
-			desc.received_signatures.push(signature);
+	/// Adds received signature for the stored private transaction. The signer must be one of the
+	/// transaction's validators, must not have signed before, and must report the same state that
+	/// was locally computed for the transaction
+	pub fn add_signature(&mut self, private_hash: &H256, signer: Address, signer_state: &Bytes, signature: Signature, journal: &mut Logging) -> Result<(), Error> {
+		let desc = self.transactions.get_mut(private_hash).ok_or_else(|| PrivateTransactionError::PrivateTransactionNotFound)?;
+		if !desc.validators.contains(&signer) {
+			return Err(PrivateTransactionError::NotAValidator.into());
 		}
+		if desc.received_signatures.contains_key(&signer) {
+			return Err(PrivateTransactionError::SignatureAlreadyReceived.into());
+		}
+		if &desc.state != signer_state {
+			return Err(PrivateTransactionError::StateMismatch.into());
+		}
+		desc.received_signatures.insert(signer, signature);
+		// Best-effort, like `remove_private_transaction`: the signature is already recorded,
+		// so a journal write failure must not be reported back as a rejected signature
+		let _ = journal.add_transition(*private_hash, TransactionState::Signed);
 		Ok(())
 	}
+
+	/// Returns `true` once every validator of the given private transaction has signed
+	pub fn quorum_reached(&self, private_hash: &H256) -> bool {
+		match self.transactions.get(private_hash) {
+			Some(desc) => !desc.validators.is_empty() && desc.validators.iter().all(|validator| desc.received_signatures.contains_key(validator)),
+			None => false,
+		}
+	}
+
+	/// Once quorum is reached, returns the original transaction together with the collected
+	/// signatures, ready for final submission. Returns `None` if quorum has not yet been reached
+	pub fn ready_for_submission(&self, private_hash: &H256) -> Option<(SignedTransaction, Vec<Signature>)> {
+		if !self.quorum_reached(private_hash) {
+			return None;
+		}
+		self.transactions.get(private_hash).map(|desc| {
+			(desc.original_transaction.clone(), desc.received_signatures.values().cloned().collect())
+		})
+	}
+}
+
+impl HeapSizeOf for SigningStore {
+	fn heap_size_of_children(&self) -> usize {
+		self.mem_usage()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use super::super::LogsConfig;
+	use ethkey::{Random, Generator};
+	use transaction::{Transaction, Action};
+
+	fn dummy_signed_transaction() -> SignedTransaction {
+		let keypair = Random.generate().unwrap();
+		Transaction {
+			action: Action::Create,
+			nonce: 0.into(),
+			gas_price: 0.into(),
+			gas: 0.into(),
+			value: 0.into(),
+			data: Vec::new(),
+		}.sign(keypair.secret(), None)
+	}
+
+	fn dummy_journal() -> Logging {
+		Logging::new(LogsConfig::new(::std::env::temp_dir().join(format!("private-tx-journal-test-{:x}", H256::random())))).unwrap()
+	}
+
+	fn store_with_one_validator() -> (SigningStore, H256, Address, Bytes) {
+		let mut store = SigningStore::new(1024 * 1024);
+		let validator = Address::random();
+		let private_hash = H256::random();
+		let state: Bytes = vec![1, 2, 3];
+		store.add_transaction(private_hash, dummy_signed_transaction(), vec![validator], state.clone()).unwrap();
+		(store, private_hash, validator, state)
+	}
+
+	#[test]
+	fn rejects_signature_from_non_validator() {
+		let (mut store, private_hash, _validator, state) = store_with_one_validator();
+		let mut journal = dummy_journal();
+		let stranger = Address::random();
+		assert!(store.add_signature(&private_hash, stranger, &state, Signature::default(), &mut journal).is_err());
+	}
+
+	#[test]
+	fn rejects_duplicate_signer() {
+		let (mut store, private_hash, validator, state) = store_with_one_validator();
+		let mut journal = dummy_journal();
+		store.add_signature(&private_hash, validator, &state, Signature::default(), &mut journal).unwrap();
+		assert!(store.add_signature(&private_hash, validator, &state, Signature::default(), &mut journal).is_err());
+	}
+
+	#[test]
+	fn rejects_mismatched_state() {
+		let (mut store, private_hash, validator, _state) = store_with_one_validator();
+		let mut journal = dummy_journal();
+		let wrong_state: Bytes = vec![9, 9, 9];
+		assert!(store.add_signature(&private_hash, validator, &wrong_state, Signature::default(), &mut journal).is_err());
+	}
+
+	#[test]
+	fn quorum_reached_only_after_all_validators_sign() {
+		let mut store = SigningStore::new(1024 * 1024);
+		let validator_a = Address::random();
+		let validator_b = Address::random();
+		let private_hash = H256::random();
+		let state: Bytes = vec![1, 2, 3];
+		store.add_transaction(private_hash, dummy_signed_transaction(), vec![validator_a, validator_b], state.clone()).unwrap();
+		let mut journal = dummy_journal();
+
+		assert!(!store.quorum_reached(&private_hash));
+		store.add_signature(&private_hash, validator_a, &state, Signature::default(), &mut journal).unwrap();
+		assert!(!store.quorum_reached(&private_hash));
+		store.add_signature(&private_hash, validator_b, &state, Signature::default(), &mut journal).unwrap();
+		assert!(store.quorum_reached(&private_hash));
+	}
+
+	#[test]
+	fn ready_for_submission_only_once_quorum_reached() {
+		let (mut store, private_hash, validator, state) = store_with_one_validator();
+		let mut journal = dummy_journal();
+
+		assert!(store.ready_for_submission(&private_hash).is_none());
+		store.add_signature(&private_hash, validator, &state, Signature::default(), &mut journal).unwrap();
+		assert!(store.ready_for_submission(&private_hash).is_some());
+	}
+}
+
+#[cfg(test)]
+mod verification_store_tests {
+	use super::*;
+	use super::super::LogsConfig;
+	use std::ops::Deref;
+	use ethkey::{Random, Generator, KeyPair};
+	use transaction::{Transaction, Action};
+	use miner::AccountDetails;
+
+	struct TestDetailsProvider;
+
+	impl TransactionQueueDetailsProvider for TestDetailsProvider {
+		fn fetch_account(&self, _address: &Address) -> AccountDetails {
+			AccountDetails {
+				nonce: 0.into(),
+				balance: U256::max_value(),
+			}
+		}
+
+		fn estimate_gas_required(&self, tx: &SignedTransaction) -> U256 {
+			tx.gas
+		}
+
+		fn is_service_transaction_acceptable(&self, _tx: &SignedTransaction) -> Result<bool, String> {
+			Ok(true)
+		}
+	}
+
+	fn unverified_transaction(keypair: &KeyPair, nonce: u64, gas_price: u64) -> UnverifiedTransaction {
+		Transaction {
+			action: Action::Create,
+			nonce: nonce.into(),
+			gas_price: gas_price.into(),
+			gas: 100_000.into(),
+			value: 0.into(),
+			data: Vec::new(),
+		}.sign(keypair.secret(), None).deref().clone()
+	}
+
+	fn fetch_nonce(_address: &Address) -> U256 { 0.into() }
+
+	fn store(limits: QueueLimits) -> (VerificationStore, Logging, TestDetailsProvider) {
+		let store = VerificationStore::new(limits);
+		let journal = Logging::new(LogsConfig::new(::std::env::temp_dir().join(format!("private-tx-verification-test-{:x}", H256::random())))).unwrap();
+		(store, journal, TestDetailsProvider)
+	}
+
+	#[test]
+	fn outbidding_transaction_evicts_the_cheaper_one() {
+		let limits = QueueLimits { max_per_sender: 1, max_count: 1, max_mem_usage: 8 * 1024 * 1024, max_age: 50 };
+		let (mut store, mut journal, provider) = store(limits);
+		let cheap = Random.generate().unwrap();
+		let rich = Random.generate().unwrap();
+
+		store.add_transaction(unverified_transaction(&cheap, 0, 1), Address::default(), Address::default(), H256::random(), &provider, 0, &mut journal, &fetch_nonce).unwrap();
+		store.add_transaction(unverified_transaction(&rich, 0, 100), Address::default(), Address::default(), H256::random(), &provider, 0, &mut journal, &fetch_nonce).unwrap();
+
+		let senders: Vec<Address> = store.ready_transactions().iter().map(|tx| tx.sender()).collect();
+		assert!(senders.contains(&rich.address()));
+		assert!(!senders.contains(&cheap.address()));
+	}
+
+	#[test]
+	fn underbidding_transaction_does_not_evict_anything() {
+		let limits = QueueLimits { max_per_sender: 1, max_count: 1, max_mem_usage: 8 * 1024 * 1024, max_age: 50 };
+		let (mut store, mut journal, provider) = store(limits);
+		let rich = Random.generate().unwrap();
+		let cheap = Random.generate().unwrap();
+
+		store.add_transaction(unverified_transaction(&rich, 0, 100), Address::default(), Address::default(), H256::random(), &provider, 0, &mut journal, &fetch_nonce).unwrap();
+		assert!(store.add_transaction(unverified_transaction(&cheap, 0, 1), Address::default(), Address::default(), H256::random(), &provider, 0, &mut journal, &fetch_nonce).is_err());
+
+		let senders: Vec<Address> = store.ready_transactions().iter().map(|tx| tx.sender()).collect();
+		assert!(senders.contains(&rich.address()));
+	}
+
+	#[test]
+	fn resending_over_per_sender_cap_only_evicts_own_earlier_transaction() {
+		// `max_count` is generous, only the per-sender cap is tight, so a failed insert here
+		// can only be this sender's own cap - it must never evict another sender's transaction
+		let limits = QueueLimits { max_per_sender: 1, max_count: 100, max_mem_usage: 8 * 1024 * 1024, max_age: 50 };
+		let (mut store, mut journal, provider) = store(limits);
+		let bystander = Random.generate().unwrap();
+		let resender = Random.generate().unwrap();
+
+		store.add_transaction(unverified_transaction(&bystander, 0, 50), Address::default(), Address::default(), H256::random(), &provider, 0, &mut journal, &fetch_nonce).unwrap();
+		store.add_transaction(unverified_transaction(&resender, 0, 1), Address::default(), Address::default(), H256::random(), &provider, 0, &mut journal, &fetch_nonce).unwrap();
+
+		// Re-sends at a higher price than its own earlier transaction, but still far cheaper
+		// than `bystander`'s: this must replace `resender`'s own entry, not `bystander`'s
+		store.add_transaction(unverified_transaction(&resender, 1, 2), Address::default(), Address::default(), H256::random(), &provider, 0, &mut journal, &fetch_nonce).unwrap();
+
+		let senders: Vec<Address> = store.ready_transactions().iter().map(|tx| tx.sender()).collect();
+		assert!(senders.contains(&bystander.address()));
+		assert!(senders.contains(&resender.address()));
+	}
+
+	#[test]
+	fn mem_usage_grows_as_transactions_are_added() {
+		let limits = QueueLimits { max_per_sender: 10, max_count: 10, max_mem_usage: 8 * 1024 * 1024, max_age: 50 };
+		let (mut store, mut journal, provider) = store(limits);
+		let before = store.mem_usage();
+
+		let sender = Random.generate().unwrap();
+		store.add_transaction(unverified_transaction(&sender, 0, 10), Address::default(), Address::default(), H256::random(), &provider, 0, &mut journal, &fetch_nonce).unwrap();
+
+		assert!(store.mem_usage() > before);
+	}
+
+	#[test]
+	fn memory_pressure_never_evicts_the_transaction_just_inserted() {
+		// A tiny byte budget means the very first insert already exceeds it; the post-insert
+		// trim loop must leave that transaction in place rather than evicting it to "fix" a
+		// budget it could never have satisfied in the first place
+		let limits = QueueLimits { max_per_sender: 10, max_count: 10, max_mem_usage: 1, max_age: 50 };
+		let (mut store, mut journal, provider) = store(limits);
+		let sender = Random.generate().unwrap();
+		let hash = unverified_transaction(&sender, 0, 10).hash();
+
+		store.add_transaction(unverified_transaction(&sender, 0, 10), Address::default(), Address::default(), H256::random(), &provider, 0, &mut journal, &fetch_nonce).unwrap();
+
+		assert!(store.private_transaction_descriptor(&hash).is_ok());
+	}
+
+	#[test]
+	fn memory_pressure_evicts_cheaper_transactions_once_the_budget_is_exceeded() {
+		let limits = QueueLimits { max_per_sender: 10, max_count: 10, max_mem_usage: 8 * 1024 * 1024, max_age: 50 };
+		let (mut store, mut journal, provider) = store(limits);
+		let cheap = Random.generate().unwrap();
+		let rich = Random.generate().unwrap();
+		let cheap_hash = unverified_transaction(&cheap, 0, 1).hash();
+
+		store.add_transaction(unverified_transaction(&cheap, 0, 1), Address::default(), Address::default(), H256::random(), &provider, 0, &mut journal, &fetch_nonce).unwrap();
+		let usage_with_one = store.mem_usage();
+		store.max_mem_usage = usage_with_one;
+		store.add_transaction(unverified_transaction(&rich, 0, 100), Address::default(), Address::default(), H256::random(), &provider, 0, &mut journal, &fetch_nonce).unwrap();
+
+		// Budget only ever allowed one transaction's worth of memory, so the cheaper,
+		// already-queued entry must have been evicted to make room under the trim loop
+		assert!(store.private_transaction_descriptor(&cheap_hash).is_err());
+		let senders: Vec<Address> = store.ready_transactions().iter().map(|tx| tx.sender()).collect();
+		assert!(senders.contains(&rich.address()));
+	}
+
+	#[test]
+	fn cull_prunes_transactions_below_the_current_nonce() {
+		let limits = QueueLimits { max_per_sender: 2, max_count: 10, max_mem_usage: 8 * 1024 * 1024, max_age: 50 };
+		let (mut store, mut journal, provider) = store(limits);
+		let sender = Random.generate().unwrap();
+		let superseded = unverified_transaction(&sender, 0, 1).hash();
+		let current = unverified_transaction(&sender, 1, 1).hash();
+
+		store.add_transaction(unverified_transaction(&sender, 0, 1), Address::default(), Address::default(), H256::random(), &provider, 0, &mut journal, &fetch_nonce).unwrap();
+		store.add_transaction(unverified_transaction(&sender, 1, 1), Address::default(), Address::default(), H256::random(), &provider, 0, &mut journal, &fetch_nonce).unwrap();
+
+		// Sender's on-chain nonce has advanced past 0, so only the nonce-0 entry is stale
+		store.cull(0, |_| 1.into());
+
+		assert!(store.private_transaction_descriptor(&superseded).is_err());
+		assert!(store.private_transaction_descriptor(&current).is_ok());
+	}
+
+	#[test]
+	fn cull_prunes_transactions_older_than_max_age() {
+		let limits = QueueLimits { max_per_sender: 1, max_count: 10, max_mem_usage: 8 * 1024 * 1024, max_age: 10 };
+		let (mut store, mut journal, provider) = store(limits);
+		let sender = Random.generate().unwrap();
+		let hash = unverified_transaction(&sender, 0, 1).hash();
+
+		store.add_transaction(unverified_transaction(&sender, 0, 1), Address::default(), Address::default(), H256::random(), &provider, 0, &mut journal, &fetch_nonce).unwrap();
+
+		// Still within max_age: nothing is culled
+		store.cull(5, fetch_nonce);
+		assert!(store.private_transaction_descriptor(&hash).is_ok());
+
+		// Past max_age: the entry is culled even though its nonce is still current
+		store.cull(20, fetch_nonce);
+		assert!(store.private_transaction_descriptor(&hash).is_err());
+	}
+
+	#[test]
+	fn cull_prunes_non_top_nonce_gapped_entries_for_a_sender() {
+		// Regression test: with `max_per_sender` above 1, a sender can have more than one
+		// queued transaction, but `top_transactions()` only ever returns its single
+		// lowest-nonce (ready) entry. `cull` must still be able to see and prune a stale,
+		// nonce-gapped entry that is not that sender's top transaction.
+		let limits = QueueLimits { max_per_sender: 2, max_count: 10, max_mem_usage: 8 * 1024 * 1024, max_age: 50 };
+		let (mut store, mut journal, provider) = store(limits);
+		let sender = Random.generate().unwrap();
+		let ready = unverified_transaction(&sender, 0, 1).hash();
+		let gapped = unverified_transaction(&sender, 5, 1).hash();
+
+		store.add_transaction(unverified_transaction(&sender, 0, 1), Address::default(), Address::default(), H256::random(), &provider, 0, &mut journal, &fetch_nonce).unwrap();
+		store.add_transaction(unverified_transaction(&sender, 5, 1), Address::default(), Address::default(), H256::random(), &provider, 0, &mut journal, &fetch_nonce).unwrap();
+
+		// Sender's nonce has advanced past 5 too, so both the ready and the gapped entry are stale
+		store.cull(0, |_| 10.into());
+
+		assert!(store.private_transaction_descriptor(&ready).is_err());
+		assert!(store.private_transaction_descriptor(&gapped).is_err());
+	}
 }